@@ -8,11 +8,19 @@ use crossterm::{
 };
 use std::collections::HashMap;
 use std::io::{self, Write};
+use std::path::Path;
+
+mod theme;
+mod tokenizer;
+
+use theme::StyleStore;
+use tokenizer::{tokenizer_for_extension, Tokenizer};
 
 pub enum Mode {
     Normal,
     Command,
     Insert,
+    Visual,
 }
 
 pub struct Cursor {
@@ -21,6 +29,14 @@ pub struct Cursor {
     pub insert: (u16, u16),
 }
 
+// A single buffer edit, recorded so it can be reversed. `removed` and
+// `inserted` may contain '\n' when the edit splits or joins lines.
+pub struct Change {
+    pub pos: (u16, u16),
+    pub removed: String,
+    pub inserted: String,
+}
+
 pub struct State {
     pub running: bool,
     pub width: u16,
@@ -30,6 +46,16 @@ pub struct State {
     pub status_bar: Vec<String>,
     pub command: String,
     pub buffer: Vec<String>,
+    pub path: Option<String>,
+    pub modified: bool,
+    pub undo: Vec<Change>,
+    pub redo: Vec<Change>,
+    pub scroll: usize,
+    pub pending: Option<char>,
+    pub anchor: (u16, u16),
+    pub register: String,
+    pub style_store: StyleStore,
+    pub tokenizer: Box<dyn Tokenizer>,
 }
 
 // Callback
@@ -59,6 +85,359 @@ fn global_map() -> HashMap<KeyEvent, Action> {
     m
 }
 
+#[derive(PartialEq, Eq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punct,
+}
+
+fn classify(c: char) -> CharClass {
+    if c == ' ' || c == '\t' {
+        CharClass::Whitespace
+    } else if c.is_ascii_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punct
+    }
+}
+
+// Treats a column past the end of its line as whitespace, so a run of the
+// current class is naturally bounded by the line break.
+fn class_at(buffer: &[String], pos: (u16, u16)) -> CharClass {
+    match buffer[pos.1 as usize].as_bytes().get(pos.0 as usize) {
+        Some(&byte) => classify(byte as char),
+        None => CharClass::Whitespace,
+    }
+}
+
+fn next_pos(buffer: &[String], pos: (u16, u16)) -> Option<(u16, u16)> {
+    let (x, y) = (pos.0 as usize, pos.1 as usize);
+    if x + 1 < buffer[y].len() {
+        Some(((x + 1) as u16, y as u16))
+    } else if y + 1 < buffer.len() {
+        Some((0, (y + 1) as u16))
+    } else {
+        None
+    }
+}
+
+fn prev_pos(buffer: &[String], pos: (u16, u16)) -> Option<(u16, u16)> {
+    let (x, y) = (pos.0 as usize, pos.1 as usize);
+    if x > 0 {
+        Some(((x - 1) as u16, y as u16))
+    } else if y > 0 {
+        let prev_len = buffer[y - 1].len();
+        let prev_x = prev_len.saturating_sub(1);
+        Some((prev_x as u16, (y - 1) as u16))
+    } else {
+        None
+    }
+}
+
+// `w`: skip the run of the current class, then skip whitespace (wrapping
+// onto following lines) to land on the first char of the next run.
+fn motion_word_forward(buffer: &[String], pos: (u16, u16)) -> (u16, u16) {
+    let start_class = class_at(buffer, pos);
+    let mut cur = pos;
+
+    if start_class != CharClass::Whitespace {
+        while let Some(next) = next_pos(buffer, cur) {
+            if next.1 != cur.1 || class_at(buffer, next) != start_class {
+                break;
+            }
+            cur = next;
+        }
+    }
+
+    match next_pos(buffer, cur) {
+        Some(next) => cur = next,
+        None => return cur,
+    }
+
+    while class_at(buffer, cur) == CharClass::Whitespace {
+        match next_pos(buffer, cur) {
+            Some(next) => cur = next,
+            None => return cur,
+        }
+    }
+
+    cur
+}
+
+// `e`: move one char forward, skip whitespace, then advance to the last
+// char of the run now under the cursor.
+fn motion_word_end(buffer: &[String], pos: (u16, u16)) -> (u16, u16) {
+    let mut cur = match next_pos(buffer, pos) {
+        Some(next) => next,
+        None => return pos,
+    };
+
+    while class_at(buffer, cur) == CharClass::Whitespace {
+        match next_pos(buffer, cur) {
+            Some(next) => cur = next,
+            None => return cur,
+        }
+    }
+
+    let run_class = class_at(buffer, cur);
+    while let Some(next) = next_pos(buffer, cur) {
+        if next.1 != cur.1 || class_at(buffer, next) != run_class {
+            break;
+        }
+        cur = next;
+    }
+
+    cur
+}
+
+// `b`: move one char back, skip whitespace backward, then move to the
+// first char of the run now under the cursor.
+fn motion_word_back(buffer: &[String], pos: (u16, u16)) -> (u16, u16) {
+    let mut cur = match prev_pos(buffer, pos) {
+        Some(prev) => prev,
+        None => return pos,
+    };
+
+    while class_at(buffer, cur) == CharClass::Whitespace {
+        match prev_pos(buffer, cur) {
+            Some(prev) => cur = prev,
+            None => return cur,
+        }
+    }
+
+    let run_class = class_at(buffer, cur);
+    while let Some(prev) = prev_pos(buffer, cur) {
+        if prev.1 != cur.1 || class_at(buffer, prev) != run_class {
+            break;
+        }
+        cur = prev;
+    }
+
+    cur
+}
+
+// Width of the line-number gutter for a file of `line_count` lines.
+fn gutter_width(line_count: usize) -> u16 {
+    ((line_count.max(1) as f64).log10().floor() as u16) + 1
+}
+
+// Number of buffer rows visible at once (the terminal minus the status bar
+// and command line).
+fn visible_rows(state: &State) -> usize {
+    state.height.saturating_sub(2) as usize
+}
+
+// Scrolls the viewport just enough to keep the cursor's row on screen.
+fn ensure_visible(state: &mut State) {
+    let rows = visible_rows(state);
+    if rows == 0 {
+        return;
+    }
+
+    let row = state.cursor_pos.normal.1 as usize;
+    if row < state.scroll {
+        state.scroll = row;
+    } else if row >= state.scroll + rows {
+        state.scroll = row + 1 - rows;
+    }
+}
+
+// Moves the cursor to `row`, clamped to the buffer, keeping normal/insert in sync.
+fn set_cursor_row(state: &mut State, row: usize) {
+    let row = row.min(state.buffer.len().saturating_sub(1)) as u16;
+    state.cursor_pos.normal.1 = row;
+    state.cursor_pos.insert.1 = row;
+}
+
+// Shared cursor movement, used by both normal and visual mode.
+fn move_left(state: &mut State) {
+    state.cursor_pos.normal.0 = state.cursor_pos.normal.0.saturating_sub(1);
+    state.cursor_pos.insert.0 = state.cursor_pos.insert.0.saturating_sub(1);
+}
+
+fn move_right(state: &mut State) {
+    let line = &state.buffer[state.cursor_pos.normal.1 as usize];
+    let length = line.len() as u16;
+    if state.cursor_pos.normal.0 < length - 1 {
+        state.cursor_pos.normal.0 += 1;
+        state.cursor_pos.insert.0 += 1;
+    }
+}
+
+fn move_down(state: &mut State) {
+    let rows = (state.buffer.len() - 1) as u16;
+    if state.cursor_pos.normal.1 < rows {
+        state.cursor_pos.normal.1 += 1;
+    }
+    ensure_visible(state);
+}
+
+fn move_up(state: &mut State) {
+    if state.cursor_pos.normal.1 > 0 {
+        state.cursor_pos.normal.1 -= 1;
+    }
+    ensure_visible(state);
+}
+
+// Returns the selection span between the visual anchor and the cursor, in
+// buffer order, inclusive of both ends.
+fn selection_bounds(state: &State) -> ((u16, u16), (u16, u16)) {
+    let a = state.anchor;
+    let b = state.cursor_pos.normal;
+    if (a.1, a.0) <= (b.1, b.0) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+// The text spanned by `start..=end`, joined with '\n' across lines - in the
+// same shape `splice_remove`/`splice_insert` expect.
+fn selection_text(buffer: &[String], start: (u16, u16), end: (u16, u16)) -> String {
+    let (sx, sy) = (start.0 as usize, start.1 as usize);
+    let (ex, ey) = (end.0 as usize, end.1 as usize);
+
+    if sy == ey {
+        let line = &buffer[sy];
+        let from = sx.min(line.len());
+        let to = (ex + 1).min(line.len()).max(from);
+        return line[from..to].to_string();
+    }
+
+    let mut lines = Vec::new();
+    let first = &buffer[sy];
+    lines.push(first[sx.min(first.len())..].to_string());
+    for line in &buffer[sy + 1..ey] {
+        lines.push(line.clone());
+    }
+    let last = &buffer[ey];
+    let to = (ex + 1).min(last.len());
+    lines.push(last[..to].to_string());
+
+    lines.join("\n")
+}
+
+fn delete_selection(state: &mut State) {
+    let (start, end) = selection_bounds(state);
+    let removed = selection_text(&state.buffer, start, end);
+
+    splice_remove(&mut state.buffer, start, &removed);
+    push_undo(state, start, removed, String::new());
+
+    state.cursor_pos.normal = start;
+    state.cursor_pos.insert = start;
+    state.modified = true;
+    ensure_visible(state);
+}
+
+fn yank_selection(state: &mut State) {
+    let (start, end) = selection_bounds(state);
+    state.register = selection_text(&state.buffer, start, end);
+
+    state.cursor_pos.normal = start;
+    state.cursor_pos.insert = start;
+    ensure_visible(state);
+}
+
+fn visual_to_normal(stdout: &mut io::Stdout, state: &mut State) -> io::Result<()> {
+    state.mode = Mode::Normal;
+    state.status_bar[0] = String::from("NORMAL");
+    draw(stdout, state)
+}
+
+fn visual_map() -> HashMap<KeyEvent, Action> {
+    let mut m = HashMap::new();
+
+    m.insert(
+        KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+        into_action(|stdout, state| visual_to_normal(stdout, state)),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('h'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            move_left(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            move_right(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            move_down(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            move_up(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let pos = motion_word_forward(&state.buffer, state.cursor_pos.normal);
+            state.cursor_pos.normal = pos;
+            state.cursor_pos.insert = pos;
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let pos = motion_word_end(&state.buffer, state.cursor_pos.normal);
+            state.cursor_pos.normal = pos;
+            state.cursor_pos.insert = pos;
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('b'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let pos = motion_word_back(&state.buffer, state.cursor_pos.normal);
+            state.cursor_pos.normal = pos;
+            state.cursor_pos.insert = pos;
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('d'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            delete_selection(state);
+            visual_to_normal(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            yank_selection(state);
+            visual_to_normal(stdout, state)
+        }),
+    );
+
+    m
+}
+
 fn normal_map() -> HashMap<KeyEvent, Action> {
     let mut m = HashMap::new();
 
@@ -102,8 +481,7 @@ fn normal_map() -> HashMap<KeyEvent, Action> {
     m.insert(
         KeyEvent::new(KeyCode::Char('h'), KeyModifiers::NONE),
         into_action(|stdout, state| {
-            state.cursor_pos.normal.0 = state.cursor_pos.normal.0.saturating_sub(1);
-            state.cursor_pos.insert.0 = state.cursor_pos.insert.0.saturating_sub(1);
+            move_left(state);
             draw(stdout, state)
         }),
     );
@@ -111,12 +489,7 @@ fn normal_map() -> HashMap<KeyEvent, Action> {
     m.insert(
         KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE),
         into_action(|stdout, state| {
-            let line = &state.buffer[state.cursor_pos.normal.1 as usize];
-            let length = line.len() as u16;
-            if state.cursor_pos.normal.0 < length - 1 {
-                state.cursor_pos.normal.0 += 1;
-                state.cursor_pos.insert.0 += 1;
-            }
+            move_right(state);
             draw(stdout, state)
         }),
     );
@@ -125,23 +498,192 @@ fn normal_map() -> HashMap<KeyEvent, Action> {
     m.insert(
         KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE),
         into_action(|stdout, state| {
-            let rows = (state.buffer.len() - 1) as u16;
+            move_down(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            move_up(state);
+            draw(stdout, state)
+        }),
+    );
+
+    // Column motions
+    m.insert(
+        KeyEvent::new(KeyCode::Char('0'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            state.cursor_pos.normal.0 = 0;
+            state.cursor_pos.insert.0 = 0;
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('$'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let line = &state.buffer[state.cursor_pos.normal.1 as usize];
+            let col = (line.len() as u16).saturating_sub(1);
+            state.cursor_pos.normal.0 = col;
+            state.cursor_pos.insert.0 = col;
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('^'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let line = &state.buffer[state.cursor_pos.normal.1 as usize];
+            let col = line
+                .bytes()
+                .position(|b| b != b' ' && b != b'\t')
+                .unwrap_or(0) as u16;
+            state.cursor_pos.normal.0 = col;
+            state.cursor_pos.insert.0 = col;
+            draw(stdout, state)
+        }),
+    );
+
+    // Word motions
+    m.insert(
+        KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let pos = motion_word_forward(&state.buffer, state.cursor_pos.normal);
+            state.cursor_pos.normal = pos;
+            state.cursor_pos.insert = pos;
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let pos = motion_word_end(&state.buffer, state.cursor_pos.normal);
+            state.cursor_pos.normal = pos;
+            state.cursor_pos.insert = pos;
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('b'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            let pos = motion_word_back(&state.buffer, state.cursor_pos.normal);
+            state.cursor_pos.normal = pos;
+            state.cursor_pos.insert = pos;
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
+
+    // Full-page scroll
+    m.insert(
+        KeyEvent::new(KeyCode::Char('f'), KeyModifiers::CONTROL),
+        into_action(|stdout, state| {
+            let rows = visible_rows(state);
+            let max_row = state.buffer.len().saturating_sub(1);
+            let max_scroll = max_row.saturating_sub(rows.saturating_sub(1));
+
+            state.scroll = (state.scroll + rows).min(max_scroll);
+            set_cursor_row(state, state.cursor_pos.normal.1 as usize + rows);
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('b'), KeyModifiers::CONTROL),
+        into_action(|stdout, state| {
+            let rows = visible_rows(state);
+
+            state.scroll = state.scroll.saturating_sub(rows);
+            set_cursor_row(state, (state.cursor_pos.normal.1 as usize).saturating_sub(rows));
+            ensure_visible(state);
+            draw(stdout, state)
+        }),
+    );
 
-            if state.cursor_pos.normal.1 < rows {
-                state.cursor_pos.normal.1 += 1;
+    // Jump to file start ('gg') / end ('G')
+    m.insert(
+        KeyEvent::new(KeyCode::Char('g'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            if state.pending == Some('g') {
+                state.pending = None;
+                set_cursor_row(state, 0);
+                ensure_visible(state);
+                draw(stdout, state)
+            } else {
+                state.pending = Some('g');
+                Ok(())
             }
+        }),
+    );
 
+    m.insert(
+        KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT),
+        into_action(|stdout, state| {
+            let last = state.buffer.len() - 1;
+            set_cursor_row(state, last);
+            ensure_visible(state);
             draw(stdout, state)
         }),
     );
 
+    // Visual mode / register
     m.insert(
-        KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE),
+        KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            state.mode = Mode::Visual;
+            state.anchor = state.cursor_pos.normal;
+            state.status_bar[0] = String::from("VISUAL");
+            draw(stdout, state)
+        }),
+    );
+
+    m.insert(
+        KeyEvent::new(KeyCode::Char('p'), KeyModifiers::NONE),
+        into_action(|stdout, state| {
+            if !state.register.is_empty() {
+                let pos = state.cursor_pos.normal;
+                let register = state.register.clone();
+                let end = splice_insert(&mut state.buffer, pos, &register);
+                push_undo(state, pos, String::new(), register);
+                state.cursor_pos.normal = end;
+                state.cursor_pos.insert = end;
+                state.modified = true;
+                ensure_visible(state);
+            }
+            draw(stdout, state)
+        }),
+    );
+
+    // Undo / redo
+    m.insert(
+        KeyEvent::new(KeyCode::Char('u'), KeyModifiers::NONE),
         into_action(|stdout, state| {
-            if state.cursor_pos.normal.1 > 0 {
-                state.cursor_pos.normal.1 -= 1;
+            if let Some(change) = state.undo.pop() {
+                let inverse = invert_change(state, change);
+                state.redo.push(inverse);
+                state.modified = true;
+                ensure_visible(state);
             }
+            draw(stdout, state)
+        }),
+    );
 
+    m.insert(
+        KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL),
+        into_action(|stdout, state| {
+            if let Some(change) = state.redo.pop() {
+                let inverse = invert_change(state, change);
+                state.undo.push(inverse);
+                state.modified = true;
+                ensure_visible(state);
+            }
             draw(stdout, state)
         }),
     );
@@ -157,6 +699,131 @@ fn command_to_normal(stdout: &mut io::Stdout, state: &mut State) -> io::Result<(
     draw(stdout, state)
 }
 
+// Removes `text` (which may span lines via '\n') from the buffer starting at `pos`.
+fn splice_remove(buffer: &mut Vec<String>, pos: (u16, u16), text: &str) {
+    if text.is_empty() {
+        return;
+    }
+
+    let (x, y) = (pos.0 as usize, pos.1 as usize);
+    let parts: Vec<&str> = text.split('\n').collect();
+
+    if parts.len() == 1 {
+        let end = x + parts[0].len();
+        buffer[y].replace_range(x..end, "");
+        return;
+    }
+
+    let last_row = y + parts.len() - 1;
+    let tail = buffer[last_row].split_off(parts[parts.len() - 1].len());
+    buffer.drain(y + 1..=last_row);
+    buffer[y].truncate(x);
+    buffer[y].push_str(&tail);
+}
+
+// Inserts `text` (which may span lines via '\n') into the buffer at `pos`.
+// Returns the position immediately after the inserted text.
+fn splice_insert(buffer: &mut Vec<String>, pos: (u16, u16), text: &str) -> (u16, u16) {
+    if text.is_empty() {
+        return pos;
+    }
+
+    let (x, y) = (pos.0 as usize, pos.1 as usize);
+    let parts: Vec<&str> = text.split('\n').collect();
+
+    if parts.len() == 1 {
+        buffer[y].insert_str(x, parts[0]);
+        return (pos.0 + parts[0].len() as u16, pos.1);
+    }
+
+    let tail = buffer[y].split_off(x);
+    buffer[y].push_str(parts[0]);
+
+    let mut row = y + 1;
+    for part in &parts[1..parts.len() - 1] {
+        buffer.insert(row, part.to_string());
+        row += 1;
+    }
+
+    let last_len = parts[parts.len() - 1].len();
+    let mut last_line = parts[parts.len() - 1].to_string();
+    last_line.push_str(&tail);
+    buffer.insert(row, last_line);
+
+    (last_len as u16, row as u16)
+}
+
+// Reverses a change in place (remove what it inserted, insert what it removed),
+// moves the cursor back to where the edit happened, and returns the change
+// needed to reverse it again (i.e. the entry for the opposite stack).
+fn invert_change(state: &mut State, change: Change) -> Change {
+    splice_remove(&mut state.buffer, change.pos, &change.inserted);
+    splice_insert(&mut state.buffer, change.pos, &change.removed);
+
+    state.cursor_pos.normal = change.pos;
+    state.cursor_pos.insert = change.pos;
+
+    Change {
+        pos: change.pos,
+        removed: change.inserted,
+        inserted: change.removed,
+    }
+}
+
+// Records an already-applied edit on the undo stack, coalescing consecutive
+// single-character insertions so one `u` removes a whole typed word. Any new
+// edit invalidates the redo stack.
+fn push_undo(state: &mut State, pos: (u16, u16), removed: String, inserted: String) {
+    if removed.is_empty() && inserted.len() == 1 && inserted != "\n" {
+        if let Some(last) = state.undo.last_mut() {
+            if last.removed.is_empty()
+                && last.pos.1 == pos.1
+                && last.pos.0 + last.inserted.len() as u16 == pos.0
+            {
+                last.inserted.push_str(&inserted);
+                state.redo.clear();
+                return;
+            }
+        }
+    }
+
+    state.undo.push(Change {
+        pos,
+        removed,
+        inserted,
+    });
+    state.redo.clear();
+}
+
+fn write_buffer(state: &mut State, path: Option<String>) {
+    let target = path.or_else(|| state.path.clone());
+
+    let target = match target {
+        Some(target) => target,
+        None => {
+            state.command = String::from("No file name.");
+            state.mode = Mode::Normal;
+            state.status_bar[0] = String::from("NORMAL");
+            state.cursor_pos.command.0 = 1;
+            return;
+        }
+    };
+
+    let contents = state.buffer.join("\n");
+    state.command = match std::fs::write(&target, &contents) {
+        Ok(()) => {
+            let message = format!("\"{}\" {}L, {}B written", target, state.buffer.len(), contents.len());
+            state.path = Some(target);
+            state.modified = false;
+            message
+        }
+        Err(err) => format!("{}", err),
+    };
+    state.mode = Mode::Normal;
+    state.status_bar[0] = String::from("NORMAL");
+    state.cursor_pos.command.0 = 1;
+}
+
 fn command_map() -> HashMap<KeyEvent, Action> {
     let mut m = HashMap::new();
 
@@ -189,8 +856,30 @@ fn command_map() -> HashMap<KeyEvent, Action> {
     m.insert(
         KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
         into_action(|stdout, state| {
-            match state.command.as_str() {
-                ":q" => state.running = false,
+            let command = state.command.clone();
+            match command.as_str() {
+                ":q" => {
+                    if state.modified {
+                        state.command = String::from("No write since last change (add ! to override).");
+                        state.mode = Mode::Normal;
+                        state.status_bar[0] = String::from("NORMAL");
+                        state.cursor_pos.command.0 = 1;
+                    } else {
+                        state.running = false;
+                    }
+                }
+                ":q!" => state.running = false,
+                ":w" => write_buffer(state, None),
+                ":wq" => {
+                    write_buffer(state, None);
+                    if !state.modified {
+                        state.running = false;
+                    }
+                }
+                cmd if cmd.starts_with(":w ") => {
+                    let path = cmd[3..].trim().to_string();
+                    write_buffer(state, Some(path));
+                }
                 _ => {
                     state.command = String::from("Unknown command.");
                     state.mode = Mode::Normal;
@@ -226,6 +915,9 @@ fn insert_map() -> HashMap<KeyEvent, Action> {
 
             state.cursor_pos.normal = (0, y + 1);
             state.cursor_pos.insert = (0, y + 1);
+            state.modified = true;
+            push_undo(state, (x, y), String::new(), String::from("\n"));
+            ensure_visible(state);
 
             draw(stdout, state)
         }),
@@ -240,7 +932,9 @@ fn insert_map() -> HashMap<KeyEvent, Action> {
             if x > 0 {
                 let index = (x - 1) as usize;
                 if index < state.buffer[ys].len() {
-                    state.buffer[ys].remove(index);
+                    let removed = state.buffer[ys].remove(index);
+                    push_undo(state, (index as u16, y), removed.to_string(), String::new());
+                    state.modified = true;
                 }
                 state.cursor_pos.normal.0 -= 1;
                 state.cursor_pos.insert.0 -= 1;
@@ -251,7 +945,10 @@ fn insert_map() -> HashMap<KeyEvent, Action> {
                 state.buffer[prev_index].push_str(&line);
                 state.cursor_pos.insert = (prev_length, prev_index as u16);
                 state.cursor_pos.normal = (prev_length, prev_index as u16);
+                push_undo(state, (prev_length, prev_index as u16), String::from("\n"), String::new());
+                state.modified = true;
             }
+            ensure_visible(state);
             draw(stdout, state)
         }),
     );
@@ -259,6 +956,70 @@ fn insert_map() -> HashMap<KeyEvent, Action> {
     m
 }
 
+// Whether column `col` of `row` falls inside the visual selection.
+fn in_selection(selection: Option<((u16, u16), (u16, u16))>, row: usize, col: usize) -> bool {
+    let (start, end) = match selection {
+        Some(bounds) => bounds,
+        None => return false,
+    };
+
+    let (sy, ey) = (start.1 as usize, end.1 as usize);
+    if row < sy || row > ey {
+        return false;
+    }
+
+    let from = if row == sy { start.0 as usize } else { 0 };
+    let to = if row == ey { end.0 as usize + 1 } else { usize::MAX };
+    col >= from && col < to
+}
+
+// Writes one buffer line, coloring each byte by its syntax token (if any)
+// and highlighting the part of it (if any) covered by the visual selection.
+fn draw_buffer_line(
+    stdout: &mut io::Stdout,
+    state: &State,
+    line: &str,
+    row: usize,
+    selection: Option<((u16, u16), (u16, u16))>,
+) -> io::Result<()> {
+    let mut colors = vec![None; line.len()];
+    for (range, kind) in state.tokenizer.tokenize(line) {
+        let color = state.style_store.color_for(kind);
+        for slot in &mut colors[range] {
+            *slot = Some(color);
+        }
+    }
+
+    let mut i = 0;
+    while i < line.len() {
+        let color = colors[i];
+        let selected = in_selection(selection, row, i);
+
+        let mut j = i + 1;
+        while j < line.len() && colors[j] == color && in_selection(selection, row, j) == selected {
+            j += 1;
+        }
+
+        if selected {
+            stdout.queue(SetBackgroundColor(Color::DarkGrey))?;
+        }
+        if let Some(color) = color {
+            stdout.queue(SetForegroundColor(color))?;
+        }
+        // Byte-slice rather than `&str`-slice: `i`/`j` are raw column/byte
+        // offsets and may fall mid multi-byte character, which a `&str`
+        // index would panic on.
+        stdout.write(&line.as_bytes()[i..j])?;
+        if selected || color.is_some() {
+            stdout.queue(ResetColor)?;
+        }
+
+        i = j;
+    }
+
+    Ok(())
+}
+
 fn draw_status_bar(stdout: &mut io::Stdout, state: &mut State) -> io::Result<()> {
     queue!(
         stdout,
@@ -284,6 +1045,10 @@ fn draw_status_bar(stdout: &mut io::Stdout, state: &mut State) -> io::Result<()>
 }
 
 fn draw(stdout: &mut io::Stdout, state: &mut State) -> io::Result<()> {
+    // Re-assert the scroll/cursor invariant here rather than trusting every
+    // caller to have called this after moving the cursor.
+    ensure_visible(state);
+
     // Clear the screen for a redraw
     stdout.queue(Clear(ClearType::All))?;
 
@@ -294,19 +1059,35 @@ fn draw(stdout: &mut io::Stdout, state: &mut State) -> io::Result<()> {
     stdout.queue(MoveTo(0, state.height - 1))?;
     stdout.write(state.command.as_bytes())?;
 
-    // Render buffer
+    // Render buffer, windowed to the visible rows and shifted past the gutter
+    let gutter_width = gutter_width(state.buffer.len()) as usize;
+    let rows = visible_rows(state);
+    let first = state.scroll;
+    let last = (first + rows).min(state.buffer.len());
+
+    let selection = match state.mode {
+        Mode::Visual => Some(selection_bounds(state)),
+        _ => None,
+    };
+
     stdout.queue(MoveTo(0, 0))?;
-    for (i, line) in state.buffer.iter().enumerate() {
-        stdout.write(line.as_bytes())?;
-        let index = i + 1;
-        stdout.queue(MoveTo(0, index as u16))?;
+    for (row, line) in state.buffer[first..last].iter().enumerate() {
+        stdout.queue(MoveTo(0, row as u16))?;
+        let gutter = format!("{:>width$}", first + row + 1, width = gutter_width);
+        stdout.write(gutter.as_bytes())?;
+        draw_buffer_line(stdout, state, line, first + row, selection)?;
     }
 
     // Mode specific
     match state.mode {
         Mode::Normal => {
             let (x, y) = state.cursor_pos.normal;
-            queue!(stdout, SetCursorStyle::SteadyBlock, MoveTo(x, y))?;
+            let screen_y = y - state.scroll as u16;
+            queue!(
+                stdout,
+                SetCursorStyle::SteadyBlock,
+                MoveTo(x + gutter_width as u16, screen_y)
+            )?;
         }
         Mode::Command => {
             let (x, y) = state.cursor_pos.command;
@@ -314,7 +1095,21 @@ fn draw(stdout: &mut io::Stdout, state: &mut State) -> io::Result<()> {
         }
         Mode::Insert => {
             let (x, y) = state.cursor_pos.insert;
-            queue!(stdout, SetCursorStyle::SteadyBar, MoveTo(x, y))?;
+            let screen_y = y - state.scroll as u16;
+            queue!(
+                stdout,
+                SetCursorStyle::SteadyBar,
+                MoveTo(x + gutter_width as u16, screen_y)
+            )?;
+        }
+        Mode::Visual => {
+            let (x, y) = state.cursor_pos.normal;
+            let screen_y = y - state.scroll as u16;
+            queue!(
+                stdout,
+                SetCursorStyle::SteadyBlock,
+                MoveTo(x + gutter_width as u16, screen_y)
+            )?;
         }
     }
 
@@ -330,6 +1125,33 @@ fn main() -> io::Result<()> {
     let normal_map = normal_map();
     let command_map = command_map();
     let insert_map = insert_map();
+    let visual_map = visual_map();
+
+    // Optional file argument, loaded into the buffer below
+    let path = std::env::args().nth(1);
+    let buffer = match &path {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let lines: Vec<String> = contents.lines().map(String::from).collect();
+                if lines.is_empty() {
+                    vec![String::new()]
+                } else {
+                    lines
+                }
+            }
+            Err(_) => vec![String::new()],
+        },
+        None => vec![String::new()],
+    };
+
+    // Syntax highlighting: theme from the config path (or built-in defaults),
+    // tokenizer picked by the opened file's extension.
+    let extension = path
+        .as_deref()
+        .and_then(|path| Path::new(path).extension())
+        .and_then(|ext| ext.to_str());
+    let style_store = StyleStore::load(theme::config_path().as_deref());
+    let tokenizer = tokenizer_for_extension(extension);
 
     // Var for stdout
     let mut stdout = io::stdout();
@@ -352,7 +1174,17 @@ fn main() -> io::Result<()> {
         },
         status_bar: vec![String::from("NORMAL")],
         command: String::new(),
-        buffer: vec![String::new()],
+        buffer,
+        path,
+        modified: false,
+        undo: Vec::new(),
+        redo: Vec::new(),
+        scroll: 0,
+        pending: None,
+        anchor: (0, 0),
+        register: String::new(),
+        style_store,
+        tokenizer,
     };
 
     // Initial draw
@@ -378,10 +1210,14 @@ fn main() -> io::Result<()> {
                     Mode::Normal => &normal_map,
                     Mode::Command => &command_map,
                     Mode::Insert => &insert_map,
+                    Mode::Visual => &visual_map,
                 };
 
                 if let Some(action) = map.get(&event) {
                     action(&mut stdout, &mut state)?;
+                    if event.code != KeyCode::Char('g') {
+                        state.pending = None;
+                    }
                     continue;
                 }
 
@@ -401,6 +1237,8 @@ fn main() -> io::Result<()> {
 
                     state.cursor_pos.insert.0 += 1;
                     state.cursor_pos.normal.0 += 1;
+                    state.modified = true;
+                    push_undo(&mut state, (insert_index as u16, row), String::new(), x.to_string());
                     draw(&mut stdout, &mut state)?;
                 }
             }
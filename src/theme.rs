@@ -0,0 +1,79 @@
+use crate::tokenizer::TokenKind;
+use crossterm::style::Color;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Maps token kinds to the color they should be drawn in.
+pub struct StyleStore {
+    colors: HashMap<TokenKind, Color>,
+}
+
+impl StyleStore {
+    pub fn color_for(&self, kind: TokenKind) -> Color {
+        self.colors.get(&kind).copied().unwrap_or(Color::White)
+    }
+
+    fn defaults() -> HashMap<TokenKind, Color> {
+        let mut colors = HashMap::new();
+        colors.insert(TokenKind::Keyword, Color::Magenta);
+        colors.insert(TokenKind::String, Color::Green);
+        colors.insert(TokenKind::Comment, Color::DarkGrey);
+        colors.insert(TokenKind::Number, Color::Cyan);
+        colors.insert(TokenKind::Identifier, Color::White);
+        colors
+    }
+
+    /// Loads a theme from a TOML file at `path`, falling back to the built-in
+    /// defaults for any color it doesn't set (or if the file is absent).
+    pub fn load(path: Option<&Path>) -> Self {
+        let mut colors = Self::defaults();
+
+        let table = path
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| contents.parse::<toml::Value>().ok());
+
+        if let Some(table) = table.as_ref().and_then(|value| value.as_table()) {
+            for (kind, key) in [
+                (TokenKind::Keyword, "keyword"),
+                (TokenKind::String, "string"),
+                (TokenKind::Comment, "comment"),
+                (TokenKind::Number, "number"),
+                (TokenKind::Identifier, "identifier"),
+            ] {
+                if let Some(color) = table.get(key).and_then(|v| v.as_str()).and_then(parse_color) {
+                    colors.insert(kind, color);
+                }
+            }
+        }
+
+        StyleStore { colors }
+    }
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    match name.to_ascii_lowercase().as_str() {
+        "black" => Some(Color::Black),
+        "red" => Some(Color::DarkRed),
+        "green" => Some(Color::DarkGreen),
+        "yellow" => Some(Color::DarkYellow),
+        "blue" => Some(Color::DarkBlue),
+        "magenta" => Some(Color::DarkMagenta),
+        "cyan" => Some(Color::DarkCyan),
+        "white" => Some(Color::Grey),
+        "grey" | "gray" => Some(Color::Grey),
+        "dark_grey" | "dark_gray" => Some(Color::DarkGrey),
+        "bright_red" => Some(Color::Red),
+        "bright_green" => Some(Color::Green),
+        "bright_yellow" => Some(Color::Yellow),
+        "bright_blue" => Some(Color::Blue),
+        "bright_magenta" => Some(Color::Magenta),
+        "bright_cyan" => Some(Color::Cyan),
+        "bright_white" => Some(Color::White),
+        _ => None,
+    }
+}
+
+/// The theme config file path, `$HOME/.config/ami/theme.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| Path::new(&home).join(".config/ami/theme.toml"))
+}
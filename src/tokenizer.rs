@@ -0,0 +1,108 @@
+use std::ops::Range;
+
+/// The kinds of token a tokenizer can classify a span of a line as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Identifier,
+}
+
+/// Given a line of text, yields the spans within it that should be colored
+/// and what kind of token each span is. Bytes not covered by any span are
+/// left in the default color.
+pub trait Tokenizer {
+    fn tokenize(&self, line: &str) -> Vec<(Range<usize>, TokenKind)>;
+}
+
+/// A line comment, string, and number aware lexer for Rust/C-style
+/// languages, sharing one combined keyword set.
+pub struct CLikeTokenizer;
+
+impl CLikeTokenizer {
+    const KEYWORDS: &'static [&'static str] = &[
+        "fn", "let", "mut", "pub", "struct", "enum", "impl", "trait", "if", "else", "match",
+        "for", "while", "loop", "return", "use", "mod", "as", "in", "break", "continue", "true",
+        "false", "int", "char", "float", "double", "void", "do", "switch", "case", "default",
+        "typedef", "unsigned", "signed", "long", "short", "sizeof", "const", "static",
+    ];
+}
+
+impl Tokenizer for CLikeTokenizer {
+    fn tokenize(&self, line: &str) -> Vec<(Range<usize>, TokenKind)> {
+        let bytes = line.as_bytes();
+        let mut spans = Vec::new();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let c = bytes[i] as char;
+
+            if c == '/' && bytes.get(i + 1) == Some(&b'/') {
+                spans.push((i..line.len(), TokenKind::Comment));
+                break;
+            }
+
+            if c == '"' {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' && i + 1 < bytes.len() { 2 } else { 1 };
+                }
+                if i < bytes.len() {
+                    i += 1;
+                }
+                spans.push((start..i, TokenKind::String));
+                continue;
+            }
+
+            if c.is_ascii_digit() {
+                let start = i;
+                while i < bytes.len() && ((bytes[i] as char).is_ascii_alphanumeric() || bytes[i] == b'.') {
+                    i += 1;
+                }
+                spans.push((start..i, TokenKind::Number));
+                continue;
+            }
+
+            if c.is_ascii_alphabetic() || c == '_' {
+                let start = i;
+                while i < bytes.len() && ((bytes[i] as char).is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                let word = &line[start..i];
+                let kind = if Self::KEYWORDS.contains(&word) {
+                    TokenKind::Keyword
+                } else {
+                    TokenKind::Identifier
+                };
+                spans.push((start..i, kind));
+                continue;
+            }
+
+            i += 1;
+        }
+
+        spans
+    }
+}
+
+/// Falls back to no highlighting for files whose language isn't recognized.
+pub struct PlainTokenizer;
+
+impl Tokenizer for PlainTokenizer {
+    fn tokenize(&self, _line: &str) -> Vec<(Range<usize>, TokenKind)> {
+        Vec::new()
+    }
+}
+
+/// Picks the tokenizer to use based on a file's extension.
+pub fn tokenizer_for_extension(extension: Option<&str>) -> Box<dyn Tokenizer> {
+    match extension {
+        Some("rs") | Some("c") | Some("h") | Some("cc") | Some("cpp") | Some("hpp") => {
+            Box::new(CLikeTokenizer)
+        }
+        _ => Box::new(PlainTokenizer),
+    }
+}